@@ -0,0 +1,169 @@
+use derive_more::Display;
+
+/// A symbology identified by its AIM (Association for Automatic Identification and Mobility)
+/// identifier, as defined by ISO/IEC 15424.
+///
+/// An AIM identifier is 3 bytes: the flag character `]`, a code letter identifying the
+/// symbology, and a modifier digit. Only the code letter is captured here; the modifier is
+/// kept separately on [`Barcode`] so it can always be round-tripped, even for symbologies we
+/// don't recognise.
+#[derive(Debug, Display, Eq, PartialEq, Clone, Copy)]
+pub enum Symbology {
+    /// `]C` - Code 128.
+    Code128,
+    /// `]E` - EAN/UPC.
+    EanUpc,
+    /// `]d` - Data Matrix.
+    DataMatrix,
+    /// `]Q` - QR Code.
+    QrCode,
+    /// `]L` - PDF417.
+    Pdf417,
+    /// `]A` - Code 39.
+    Code39,
+    /// `]I` - Interleaved 2-of-5.
+    Interleaved2of5,
+    /// An AIM identifier we don't recognise, kept as the raw 3 bytes so it can still be
+    /// round-tripped.
+    #[display(fmt = "Unknown({:02x?})", _0)]
+    Unknown([u8; 3]),
+}
+
+impl Symbology {
+    /// Maps a 3-byte AIM symbology identifier (`]` + code letter + modifier digit) to a
+    /// [`Symbology`].
+    pub fn from_aim(aim: &[u8; 3]) -> Symbology {
+        match aim[1] {
+            b'C' => Symbology::Code128,
+            b'E' => Symbology::EanUpc,
+            b'd' => Symbology::DataMatrix,
+            b'Q' => Symbology::QrCode,
+            b'L' => Symbology::Pdf417,
+            b'A' => Symbology::Code39,
+            b'I' => Symbology::Interleaved2of5,
+            _ => Symbology::Unknown(*aim),
+        }
+    }
+}
+
+/// A decoded barcode scan, as read from a single HID POS input report.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Barcode {
+    /// The symbology the scanner identified the code as.
+    pub symbology: Symbology,
+    /// The AIM modifier digit, kept as-is for round-tripping.
+    pub modifier: u8,
+    /// The raw scanned data.
+    ///
+    /// Kept alongside `data_as_utf8` because not every symbology guarantees valid UTF-8 data -
+    /// e.g. Data Matrix's binary compaction mode can pack arbitrary bytes.
+    pub data: Vec<u8>,
+    /// A lossy UTF-8 view of `data`, convenient for symbologies that only ever carry text.
+    pub data_as_utf8: String,
+}
+
+/// Parses a single HID POS input report into a [`Barcode`].
+///
+/// A HID POS barcode report is laid out as:
+///
+/// ```text
+/// [ length ][ ...decoded data... ][ 3-byte AIM symbology identifier ]
+/// ```
+///
+/// `length` is the number of bytes that follow it and are significant (the data plus the AIM
+/// identifier); anything after that in a fixed-size report is padding and is ignored.
+///
+/// Returns `None` if `report` is too short to contain a length byte and a full AIM identifier,
+/// or if the length byte claims more bytes than were actually read.
+pub fn decode(report: &[u8]) -> Option<Barcode> {
+    let length = *report.first()? as usize;
+    let end = 1 + length;
+
+    if length < 3 || report.len() < end {
+        return None;
+    }
+
+    let payload = &report[1..end];
+    let aim_offset = payload.len() - 3;
+
+    let mut aim = [0u8; 3];
+    aim.copy_from_slice(&payload[aim_offset..]);
+
+    let data = payload[..aim_offset].to_vec();
+    let data_as_utf8 = String::from_utf8_lossy(&data).into_owned();
+
+    Some(Barcode {
+        symbology: Symbology::from_aim(&aim),
+        modifier: aim[2],
+        data,
+        data_as_utf8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_aim_maps_known_code_letters() {
+        assert_eq!(Symbology::from_aim(b"]C0"), Symbology::Code128);
+        assert_eq!(Symbology::from_aim(b"]E0"), Symbology::EanUpc);
+        assert_eq!(Symbology::from_aim(b"]d2"), Symbology::DataMatrix);
+        assert_eq!(Symbology::from_aim(b"]Q1"), Symbology::QrCode);
+        assert_eq!(Symbology::from_aim(b"]L0"), Symbology::Pdf417);
+        assert_eq!(Symbology::from_aim(b"]A0"), Symbology::Code39);
+        assert_eq!(Symbology::from_aim(b"]I1"), Symbology::Interleaved2of5);
+    }
+
+    #[test]
+    fn from_aim_returns_unknown_for_unrecognised_code_letters() {
+        assert_eq!(Symbology::from_aim(b"]Z0"), Symbology::Unknown(*b"]Z0"));
+    }
+
+    #[test]
+    fn decode_returns_none_for_empty_report() {
+        assert_eq!(decode(&[]), None);
+    }
+
+    #[test]
+    fn decode_returns_none_when_length_is_shorter_than_an_aim_identifier() {
+        // length byte says 2 bytes follow, but a valid report needs at least 3 (the AIM id).
+        assert_eq!(decode(&[2, b']', b'C']), None);
+    }
+
+    #[test]
+    fn decode_returns_none_when_length_claims_more_bytes_than_were_read() {
+        // length byte says 10 bytes follow, but only 3 are actually present.
+        assert_eq!(decode(&[10, b']', b'C', b'0']), None);
+    }
+
+    #[test]
+    fn decode_parses_code128_text_data() {
+        let mut report = vec![9]; // length: 6 bytes of data + 3-byte AIM id
+        report.extend_from_slice(b"123456");
+        report.extend_from_slice(b"]C0");
+        report.extend_from_slice(&[0, 0, 0]); // trailing padding in a fixed-size report
+
+        let barcode = decode(&report).unwrap();
+
+        assert_eq!(barcode.symbology, Symbology::Code128);
+        assert_eq!(barcode.modifier, b'0');
+        assert_eq!(barcode.data, b"123456");
+        assert_eq!(barcode.data_as_utf8, "123456");
+    }
+
+    #[test]
+    fn decode_keeps_raw_bytes_for_non_utf8_data_matrix_payloads() {
+        let mut report = vec![7]; // length: 4 bytes of binary data + 3-byte AIM id
+        let binary_data = [0xff, 0x00, 0xfe, 0x80];
+        report.extend_from_slice(&binary_data);
+        report.extend_from_slice(b"]d2");
+
+        let barcode = decode(&report).unwrap();
+
+        assert_eq!(barcode.symbology, Symbology::DataMatrix);
+        assert_eq!(barcode.modifier, b'2');
+        assert_eq!(barcode.data, binary_data);
+        assert_eq!(barcode.data_as_utf8, String::from_utf8_lossy(&binary_data));
+    }
+}
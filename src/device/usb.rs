@@ -63,6 +63,18 @@ pub struct Endpoint {
     b_interval: u8,
 }
 
+impl Endpoint {
+    /// The endpoint's address, as used in USB transfer calls.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// The endpoint's transfer type (control, isochronous, bulk or interrupt).
+    pub fn transfer_type(&self) -> TransferType {
+        self.transfer_type
+    }
+}
+
 impl Display for Endpoint {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(
@@ -77,17 +89,17 @@ impl Display for Endpoint {
 /// Convert to USB speed standard to human readable value.
 pub fn speed_as_str(speed: &Speed) -> &'static str {
     match speed {
-        Speed::Unknown => "5000 Mbps",
-        Speed::Low => "480 Mbps",
+        Speed::Unknown => "(unknown)",
+        Speed::Low => "1.5 Mbps",
         Speed::Full => "12 Mbps",
-        Speed::High => "1.5 Mbps",
-        Speed::Super => "(unknown)",
+        Speed::High => "480 Mbps",
+        Speed::Super => "5000 Mbps",
     }
 }
 
 /// Finds a readable endpoint of a specified transfer type.
 pub fn find_readable_endpoint<T: UsbContext>(
-    usb_device_handle: UsbDeviceHandle<T>,
+    usb_device_handle: &UsbDeviceHandle<T>,
     transfer_type: TransferType,
 ) -> Option<Endpoint> {
     info!(
@@ -134,6 +146,55 @@ pub fn find_readable_endpoint<T: UsbContext>(
     None
 }
 
+/// Finds a writable endpoint of a specified transfer type.
+pub fn find_writable_endpoint<T: UsbContext>(
+    usb_device_handle: &UsbDeviceHandle<T>,
+    transfer_type: TransferType,
+) -> Option<Endpoint> {
+    info!(
+        "Looking for the first writable endpoint with transfer type {:?}.",
+        transfer_type
+    );
+
+    // iterate over all configurations, pick the first one that's writable:
+    for n in 0..usb_device_handle.device_desc.num_configurations() {
+        let config_desc = match usb_device_handle.device.config_descriptor(n) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        // find the first endpoint that's Direction::Out and the requested transfer type:
+        for interface in config_desc.interfaces() {
+            for interface_desc in interface.descriptors() {
+                for endpoint_desc in interface_desc.endpoint_descriptors() {
+                    if endpoint_desc.direction() == Direction::Out
+                        && endpoint_desc.transfer_type() == transfer_type
+                    {
+                        return Some(Endpoint {
+                            config: config_desc.number(),
+                            iface: interface_desc.interface_number(),
+                            setting: interface_desc.setting_number(),
+                            address: endpoint_desc.address(),
+                            direction: endpoint_desc.direction(),
+                            transfer_type: endpoint_desc.transfer_type(),
+                            sync_type: endpoint_desc.sync_type(),
+                            usage_type: endpoint_desc.usage_type(),
+                            max_packet_size: endpoint_desc.max_packet_size(),
+                            b_interval: endpoint_desc.interval(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    info!(
+        "No writable endpoint found with transfer type {:?}.",
+        transfer_type
+    );
+    None
+}
+
 /// Open a USB device and get back a device handle.
 pub fn open_usb_device<T: UsbContext>(
     context: &mut T,
@@ -209,6 +270,52 @@ pub fn configure_endpoint<T: UsbContext>(
     Ok(())
 }
 
+/// Sends data to a writable endpoint, e.g. an output report or a vendor command.
+///
+/// Control endpoints are written with a `SET_REPORT`-style host-to-device class request;
+/// interrupt and bulk endpoints are written directly.
+pub fn write_device<T: UsbContext>(
+    usb_device_handle: &mut UsbDeviceHandle<T>,
+    endpoint: &Endpoint,
+    data: &[u8],
+    timeout: Duration,
+) -> rusb::Result<usize> {
+    info!(
+        "Writing {} bytes to endpoint {:#04x}.",
+        data.len(),
+        endpoint.address
+    );
+
+    match endpoint.transfer_type {
+        TransferType::Interrupt => {
+            usb_device_handle
+                .handle
+                .write_interrupt(endpoint.address, data, timeout)
+        }
+        TransferType::Bulk => usb_device_handle
+            .handle
+            .write_bulk(endpoint.address, data, timeout),
+        TransferType::Control => {
+            // HID SET_REPORT: bmRequestType = Host-to-device | Class | Interface.
+            const HID_SET_REPORT: u8 = 0x09;
+            const HID_REPORT_TYPE_OUTPUT: u16 = 0x02 << 8;
+
+            usb_device_handle.handle.write_control(
+                0x21,
+                HID_SET_REPORT,
+                HID_REPORT_TYPE_OUTPUT,
+                endpoint.iface as u16,
+                data,
+                timeout,
+            )
+        }
+        TransferType::Isochronous => {
+            error!("Isochronous transfers aren't supported for writing.");
+            Ok(0)
+        }
+    }
+}
+
 pub fn pool_interrupt_endpoint<T: UsbContext>(
     usb_device_handle: &mut UsbDeviceHandle<T>,
     endpoint: self::Endpoint,
@@ -285,3 +392,289 @@ pub fn pool_interrupt_endpoint<T: UsbContext>(
             .ok();
     }
 }
+
+/// An endpoint, as reported by [`enumerate_devices`].
+#[derive(Debug, Clone)]
+pub struct UsbEndpointReport {
+    pub address: u8,
+    pub direction: Direction,
+    pub transfer_type: TransferType,
+    pub max_packet_size: u16,
+    pub b_interval: u8,
+}
+
+/// An interface and alternate setting, as reported by [`enumerate_devices`].
+#[derive(Debug, Clone)]
+pub struct UsbInterfaceReport {
+    pub number: u8,
+    pub alternate_setting: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub endpoints: Vec<UsbEndpointReport>,
+}
+
+/// A configuration, as reported by [`enumerate_devices`].
+#[derive(Debug, Clone)]
+pub struct UsbConfigReport {
+    pub number: u8,
+    pub interfaces: Vec<UsbInterfaceReport>,
+}
+
+/// The full descriptor tree of a single attached USB device, as reported by
+/// [`enumerate_devices`].
+#[derive(Debug, Clone)]
+pub struct UsbDeviceReport {
+    pub bus_number: u8,
+    pub address: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub class: u8,
+    pub bcd_usb: u16,
+    pub speed: Speed,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    pub configurations: Vec<UsbConfigReport>,
+}
+
+impl UsbDeviceReport {
+    /// Prints this device's descriptor tree as an indented, human-readable tree, in the style
+    /// of `lsusb -v`.
+    pub fn print_tree(&self) {
+        println!(
+            "Bus {:03} Device {:03}: ID {:04x}:{:04x} {} {}",
+            self.bus_number,
+            self.address,
+            self.vendor_id,
+            self.product_id,
+            self.manufacturer.as_deref().unwrap_or("NA"),
+            self.product.as_deref().unwrap_or("NA"),
+        );
+        println!("  bDeviceClass     {:#04x}", self.class);
+        println!("  bcdUSB           {:#06x}", self.bcd_usb);
+        println!("  Speed            {}", speed_as_str(&self.speed));
+        if let Some(serial) = &self.serial_number {
+            println!("  iSerialNumber    {}", serial);
+        }
+
+        for config in &self.configurations {
+            println!("  Configuration {}", config.number);
+
+            for interface in &config.interfaces {
+                println!(
+                    "    Interface {}, Alt {}: class {:#04x} subclass {:#04x} protocol {:#04x}",
+                    interface.number,
+                    interface.alternate_setting,
+                    interface.class,
+                    interface.subclass,
+                    interface.protocol,
+                );
+
+                for endpoint in &interface.endpoints {
+                    println!(
+                        "      Endpoint {:#04x}: {:?} {:?}, max packet size {}, interval {}",
+                        endpoint.address,
+                        endpoint.direction,
+                        endpoint.transfer_type,
+                        endpoint.max_packet_size,
+                        endpoint.b_interval,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Serializes this report as JSON.
+    ///
+    /// Hand-rolled rather than pulled in from a serialization crate, since this is the only
+    /// place in the crate that needs it.
+    pub fn to_json(&self) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+
+        fn json_string_or_null(s: &Option<String>) -> String {
+            match s {
+                Some(s) => format!("\"{}\"", escape(s)),
+                None => "null".to_string(),
+            }
+        }
+
+        let configurations = self
+            .configurations
+            .iter()
+            .map(|config| {
+                let interfaces = config
+                    .interfaces
+                    .iter()
+                    .map(|interface| {
+                        let endpoints = interface
+                            .endpoints
+                            .iter()
+                            .map(|endpoint| {
+                                format!(
+                                    "{{\"address\":{},\"direction\":\"{:?}\",\"transfer_type\":\"{:?}\",\"max_packet_size\":{},\"b_interval\":{}}}",
+                                    endpoint.address,
+                                    endpoint.direction,
+                                    endpoint.transfer_type,
+                                    endpoint.max_packet_size,
+                                    endpoint.b_interval
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join(",");
+
+                        format!(
+                            "{{\"number\":{},\"alternate_setting\":{},\"class\":{},\"subclass\":{},\"protocol\":{},\"endpoints\":[{}]}}",
+                            interface.number,
+                            interface.alternate_setting,
+                            interface.class,
+                            interface.subclass,
+                            interface.protocol,
+                            endpoints
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                format!(
+                    "{{\"number\":{},\"interfaces\":[{}]}}",
+                    config.number, interfaces
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{{\"bus_number\":{},\"address\":{},\"vendor_id\":{},\"product_id\":{},\"class\":{},\"bcd_usb\":{},\"speed\":\"{:?}\",\"manufacturer\":{},\"product\":{},\"serial_number\":{},\"configurations\":[{}]}}",
+            self.bus_number,
+            self.address,
+            self.vendor_id,
+            self.product_id,
+            self.class,
+            self.bcd_usb,
+            self.speed,
+            json_string_or_null(&self.manufacturer),
+            json_string_or_null(&self.product),
+            json_string_or_null(&self.serial_number),
+            configurations
+        )
+    }
+}
+
+/// Walks every currently-attached USB device and dumps its full descriptor tree: the device
+/// descriptor, negotiated speed, every configuration, interface and alternate setting, every
+/// endpoint, and the decoded manufacturer/product/serial string descriptors.
+///
+/// This is `lsusb`'s job, built in, so users can discover the VID/PID/serial to put into a
+/// [`crate::devices::UsbDeviceIdentifier`] without needing an external tool.
+pub fn enumerate_devices<T: UsbContext>(context: &mut T) -> Vec<UsbDeviceReport> {
+    info!("Enumerating attached USB devices.");
+
+    let timeout = Duration::from_secs(1);
+    let mut reports = Vec::new();
+
+    let devices = match context.devices() {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Failed to get a list of current USB devices: {}", e);
+            return reports;
+        }
+    };
+
+    for device in devices.iter() {
+        let device_desc = match device.device_descriptor() {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Failed to get device descriptor: {}", e);
+                continue;
+            }
+        };
+
+        let handle = device.open().ok();
+        let language = handle
+            .as_ref()
+            .and_then(|h| h.read_languages(timeout).ok())
+            .and_then(|languages| languages.into_iter().next());
+
+        let string_descriptor = |f: fn(
+            &DeviceHandle<T>,
+            Language,
+            &DeviceDescriptor,
+            Duration,
+        ) -> rusb::Result<String>| {
+            match (&handle, language) {
+                (Some(h), Some(l)) => f(h, l, &device_desc, timeout).ok(),
+                _ => None,
+            }
+        };
+
+        let manufacturer = string_descriptor(DeviceHandle::read_manufacturer_string);
+        let product = string_descriptor(DeviceHandle::read_product_string);
+        let serial_number = string_descriptor(DeviceHandle::read_serial_number_string);
+
+        let mut configurations = Vec::new();
+
+        for n in 0..device_desc.num_configurations() {
+            let config_desc = match device.config_descriptor(n) {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+
+            let mut interfaces = Vec::new();
+
+            for interface in config_desc.interfaces() {
+                for interface_desc in interface.descriptors() {
+                    let endpoints = interface_desc
+                        .endpoint_descriptors()
+                        .map(|endpoint_desc| UsbEndpointReport {
+                            address: endpoint_desc.address(),
+                            direction: endpoint_desc.direction(),
+                            transfer_type: endpoint_desc.transfer_type(),
+                            max_packet_size: endpoint_desc.max_packet_size(),
+                            b_interval: endpoint_desc.interval(),
+                        })
+                        .collect();
+
+                    interfaces.push(UsbInterfaceReport {
+                        number: interface_desc.interface_number(),
+                        alternate_setting: interface_desc.setting_number(),
+                        class: interface_desc.class_code(),
+                        subclass: interface_desc.sub_class_code(),
+                        protocol: interface_desc.protocol_code(),
+                        endpoints,
+                    });
+                }
+            }
+
+            configurations.push(UsbConfigReport {
+                number: config_desc.number(),
+                interfaces,
+            });
+        }
+
+        let usb_version = device_desc.usb_version();
+        let bcd_usb = ((usb_version.major() as u16) << 8)
+            | ((usb_version.minor() as u16) << 4)
+            | usb_version.sub_minor() as u16;
+
+        reports.push(UsbDeviceReport {
+            bus_number: device.bus_number(),
+            address: device.address(),
+            vendor_id: device_desc.vendor_id(),
+            product_id: device_desc.product_id(),
+            class: device_desc.class_code(),
+            bcd_usb,
+            speed: device.speed(),
+            manufacturer,
+            product,
+            serial_number,
+            configurations,
+        });
+    }
+
+    info!("Found {} USB device(s).", reports.len());
+
+    reports
+}
@@ -6,11 +6,7 @@ use derive_more::Display;
 #[derive(Debug, Display, Eq, PartialEq, Clone, Copy)]
 pub enum UsbDeviceIdentifier<'a> {
     /// A device matching a vendor ID (vid) and a product ID (pid).
-    #[display(
-        fmt = "VidPid {{ vid: {:04x?}, pid: {:04x?} }}",
-        vid,
-        pid
-    )]
+    #[display(fmt = "VidPid {{ vid: {:04x?}, pid: {:04x?} }}", vid, pid)]
     VidPid { vid: u16, pid: u16 },
 
     /// A device matching a vendor ID (vid), a product ID (pid) and a (usually) unique product
@@ -23,3 +19,29 @@ pub enum UsbDeviceIdentifier<'a> {
     )]
     VidPidSn { vid: u16, pid: u16, sn: &'a str },
 }
+
+impl<'a> UsbDeviceIdentifier<'a> {
+    /// This device's vendor ID.
+    pub fn vendor_id(&self) -> u16 {
+        match self {
+            UsbDeviceIdentifier::VidPid { vid, .. } => *vid,
+            UsbDeviceIdentifier::VidPidSn { vid, .. } => *vid,
+        }
+    }
+
+    /// This device's product ID.
+    pub fn product_id(&self) -> u16 {
+        match self {
+            UsbDeviceIdentifier::VidPid { pid, .. } => *pid,
+            UsbDeviceIdentifier::VidPidSn { pid, .. } => *pid,
+        }
+    }
+
+    /// This device's serial number, if it's being matched on one.
+    pub fn serial_number(&self) -> Option<&'a str> {
+        match self {
+            UsbDeviceIdentifier::VidPid { .. } => None,
+            UsbDeviceIdentifier::VidPidSn { sn, .. } => Some(sn),
+        }
+    }
+}
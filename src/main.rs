@@ -1,8 +1,12 @@
 use std::time::Duration;
 
+use rusb::TransferType;
+
 use crate::constants::{PID_SG20, VID_INTERMEC};
+use crate::device::usb::{enumerate_devices, find_readable_endpoint, open_usb_device};
 use crate::devices::UsbDeviceIdentifier;
 use crate::server::UsbHidPosDeviceServer;
+use crate::usbip::{ExportedDevice, RusbInterfaceHandler, UsbIpServer, USBIP_PORT};
 
 // USB HID POS: (in Honeywell user guides referenced as USB HID / USB HID Bar Code Scanner)
 //
@@ -27,13 +31,26 @@ use crate::server::UsbHidPosDeviceServer;
 
 pub mod constants;
 pub mod decoder;
+pub mod device;
 pub mod devices;
 pub mod server;
 pub mod tools;
+pub mod usbip;
 
 #[tracing::instrument]
 fn main() {
-    tools::initialize_logging(false);
+    let args: Vec<String> = std::env::args().collect();
+    let json_output = args.iter().any(|a| a == "--json");
+
+    tools::initialize_logging(json_output);
+
+    if args.iter().any(|a| a == "--list") {
+        return list_devices(json_output);
+    }
+
+    if args.iter().any(|a| a == "--usbip") {
+        return start_usbip_server();
+    }
 
     let device_id = UsbDeviceIdentifier::VidPidSn {
         vid: VID_INTERMEC,
@@ -45,3 +62,72 @@ fn main() {
 
     server.start(Duration::from_secs(5));
 }
+
+/// Implements `--usbip`: shares the scanner over the network over the USB/IP protocol, so a
+/// remote host can attach it as if it were local.
+fn start_usbip_server() {
+    let mut context = match rusb::Context::new() {
+        Ok(context) => context,
+        Err(e) => {
+            eprintln!("Failed to create a libusb context: {:?}", e);
+            return;
+        }
+    };
+
+    let usb_device_handle = match open_usb_device(&mut context, VID_INTERMEC, PID_SG20) {
+        Some(handle) => handle,
+        None => {
+            eprintln!("No matching USB device found to export over USB/IP.");
+            return;
+        }
+    };
+
+    let exported_device = match ExportedDevice::from_handle(&usb_device_handle) {
+        Some(d) => d,
+        None => {
+            eprintln!("Failed to read the device's configuration descriptor.");
+            return;
+        }
+    };
+
+    let endpoint = match find_readable_endpoint(&usb_device_handle, TransferType::Interrupt) {
+        Some(e) => e,
+        None => {
+            eprintln!("No readable interrupt endpoint found on the device.");
+            return;
+        }
+    };
+
+    let handler = RusbInterfaceHandler::new(usb_device_handle, endpoint, Duration::from_secs(1));
+    let mut server = UsbIpServer::new(exported_device, handler);
+
+    if let Err(e) = server.start(USBIP_PORT) {
+        eprintln!("USB/IP server error: {:?}", e);
+    }
+}
+
+/// Implements `--list`: dumps the descriptor tree of every attached USB device, lsusb-style.
+fn list_devices(json_output: bool) {
+    let mut context = match rusb::Context::new() {
+        Ok(context) => context,
+        Err(e) => {
+            eprintln!("Failed to create a libusb context: {:?}", e);
+            return;
+        }
+    };
+
+    let reports = enumerate_devices(&mut context);
+
+    if json_output {
+        let devices = reports
+            .iter()
+            .map(|report| report.to_json())
+            .collect::<Vec<_>>()
+            .join(",");
+        println!("[{}]", devices);
+    } else {
+        for report in &reports {
+            report.print_tree();
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use std::sync::{mpsc, Mutex};
+use std::thread;
 use std::thread::sleep;
 use std::time::Duration;
 
-use hidapi::{HidApi, HidResult};
-use tracing::{debug, info};
+use hidapi::{HidApi, HidDevice, HidError, HidResult};
+use rusb::{Context, Device, Hotplug, HotplugBuilder, UsbContext};
+use tracing::{debug, error, info};
 
+use crate::decoder;
 use crate::devices::UsbDeviceIdentifier;
 use crate::tools::{get_product_name, refresh_devices};
 
@@ -18,9 +22,24 @@ pub fn initialize_hidapi() -> HidResult<HidApi> {
     hidapi::HidApi::new()
 }
 
-#[derive(Debug)]
 pub struct UsbHidPosDeviceServer<'a> {
     device_identifier: UsbDeviceIdentifier<'a>,
+    /// The device currently being read from, if any.
+    ///
+    /// `hidapi::HidDevice` is `Send` but not `Sync` (its methods go straight to the native
+    /// handle with no internal locking), so every access to it - including the read loop's own
+    /// reads - goes through this lock. That serializes all device I/O instead of just sharing a
+    /// handle, which is what actually makes it sound to call `send_command`/`write_report` from
+    /// another thread while the read loop is running.
+    device: Mutex<Option<HidDevice>>,
+}
+
+impl<'a> std::fmt::Debug for UsbHidPosDeviceServer<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UsbHidPosDeviceServer")
+            .field("device_identifier", &self.device_identifier)
+            .finish()
+    }
 }
 
 impl<'a> UsbHidPosDeviceServer<'a> {
@@ -31,94 +50,184 @@ impl<'a> UsbHidPosDeviceServer<'a> {
             device_identifier
         );
 
-        UsbHidPosDeviceServer { device_identifier }
+        UsbHidPosDeviceServer {
+            device_identifier,
+            device: Mutex::new(None),
+        }
+    }
+
+    fn not_connected_error() -> HidError {
+        HidError::HidApiError {
+            message: "no device is currently connected".to_string(),
+        }
+    }
+
+    /// Sends a vendor command to the device, e.g. to trigger a soft scan or a good-read beep.
+    ///
+    /// `command` is the vendor command byte, followed by `payload` as its arguments. Returns
+    /// an error if no device is currently connected.
+    #[tracing::instrument(skip(self, payload))]
+    pub fn send_command(&self, command: u8, payload: &[u8]) -> HidResult<usize> {
+        let guard = self.device.lock().unwrap();
+        let device = guard.as_ref().ok_or_else(Self::not_connected_error)?;
+
+        let mut report = Vec::with_capacity(1 + payload.len());
+        report.push(command);
+        report.extend_from_slice(payload);
+
+        debug!(
+            "Sending command {:#04x} with {} byte(s) of payload.",
+            command,
+            payload.len()
+        );
+
+        device.write(&report)
     }
 
+    /// Writes a raw output report to the device, e.g. to enable or disable a symbology.
+    /// Returns an error if no device is currently connected.
+    #[tracing::instrument(skip(self, report))]
+    pub fn write_report(&self, report: &[u8]) -> HidResult<usize> {
+        let guard = self.device.lock().unwrap();
+        let device = guard.as_ref().ok_or_else(Self::not_connected_error)?;
+
+        debug!("Writing a {} byte report.", report.len());
+
+        device.write(report)
+    }
+
+    /// Writes a feature report to the device, used for persistent configuration rather than
+    /// one-off commands. Returns an error if no device is currently connected.
+    #[tracing::instrument(skip(self, report))]
+    pub fn send_feature_report(&self, report: &[u8]) -> HidResult<()> {
+        let guard = self.device.lock().unwrap();
+        let device = guard.as_ref().ok_or_else(Self::not_connected_error)?;
+
+        debug!("Sending a {} byte feature report.", report.len());
+
+        device.send_feature_report(report)
+    }
+
+    /// Starts the server: waits for the device to become available, then reads from it until
+    /// it disconnects, repeating forever.
+    ///
+    /// Detection prefers libusb hotplug notifications, which make reconnection near-instant
+    /// and avoid idle polling entirely. `timeout` is only used as the polling interval on
+    /// platforms where libusb reports hotplug support is unavailable, or when a libusb context
+    /// can't be created.
     #[tracing::instrument()]
     pub fn start(&self, timeout: Duration) {
         info!("Starting server.");
 
-        loop {
-            let hidapi_init_result = initialize_hidapi();
+        if !rusb::has_hotplug() {
+            info!("libusb hotplug isn't supported on this platform, falling back to polling.");
+            return self.start_with_polling(timeout);
+        }
 
-            match hidapi_init_result {
-                Ok(mut hidapi) => loop {
-                    let present_devices = refresh_devices(&mut hidapi);
+        match Context::new() {
+            Ok(context) => self.start_with_hotplug(context),
+            Err(e) => {
+                error!(
+                    "Failed to create a libusb context ({:?}), falling back to polling.",
+                    e
+                );
+                self.start_with_polling(timeout);
+            }
+        }
+    }
 
-                    if present_devices.contains(&self.device_identifier) {
-                        info!("Device is present: {}", self.device_identifier);
+    /// Opens the device once it's present and reads from it until it disconnects or 3
+    /// consecutive reads fail.
+    fn connect_and_read(&self, hidapi: &HidApi) {
+        let device_response = match self.device_identifier {
+            UsbDeviceIdentifier::VidPid { vid, pid } => hidapi.open(vid, pid),
+            UsbDeviceIdentifier::VidPidSn { vid, pid, sn } => hidapi.open_serial(vid, pid, sn),
+        };
+
+        match device_response {
+            Ok(device) => {
+                info!("Connected to {}", self.device_identifier);
+
+                let product_name = get_product_name(&device);
+
+                info!("Device name: {}.", product_name);
+
+                *self.device.lock().unwrap() = Some(device);
+
+                const BUFFER_SIZE: usize = 64 * 4;
+                const READ_TIMEOUT_MS: i32 = 100;
+                let mut buf = [0u8; BUFFER_SIZE];
+
+                info!("Entering read loop.");
+
+                let mut num_read_errors = 0;
 
-                        let device_response = {
-                            match self.device_identifier {
-                                UsbDeviceIdentifier::VidPid { vid, pid } => hidapi.open(vid, pid),
-                                UsbDeviceIdentifier::VidPidSn { vid, pid, sn } => {
-                                    hidapi.open_serial(vid, pid, sn)
+                loop {
+                    info!("Waiting for read...");
+
+                    // Only the read itself needs the lock; releasing it between reads is what
+                    // lets `send_command`/`write_report` actually get a turn while this loop is
+                    // blocked waiting for the next report.
+                    let read_result = {
+                        let guard = self.device.lock().unwrap();
+                        guard.as_ref().unwrap().read_timeout(&mut buf, READ_TIMEOUT_MS)
+                    };
+
+                    match read_result {
+                        // The read timed out with no report; release the lock and wait again.
+                        Ok(0) => continue,
+                        Ok(read_len) => {
+                            num_read_errors = 0;
+
+                            let bytes = &buf[..read_len];
+
+                            debug!("Received {} bytes: {:02x?}", bytes.len(), bytes);
+
+                            match decoder::decode(bytes) {
+                                Some(barcode) => {
+                                    info!("Scanned barcode: {:?}", barcode);
                                 }
-                            }
-                        };
-
-                        match device_response {
-                            Ok(device) => {
-                                info!("Connected to {}", self.device_identifier);
-
-                                let product_name = get_product_name(&device);
-
-                                info!("Device name: {}.", product_name);
-
-                                const BUFFER_SIZE: usize = 64 * 4;
-                                let mut buf = [0u8; BUFFER_SIZE];
-                                let mut _data_buf: Vec<u8>;
-
-                                info!("Entering read loop.");
-
-                                let mut num_read_errors = 0;
-
-                                loop {
-                                    info!("Waiting for read...");
-
-                                    let read_result = device.read(&mut buf);
-
-                                    match read_result {
-                                        Ok(read_len) => {
-                                            num_read_errors = 0;
-
-                                            let bytes = &buf[..read_len];
-                                            let _symbology_bytes = {
-                                                let mut sym = [0u8; 3];
-                                                sym.copy_from_slice(&bytes[2..=4]);
-                                                sym
-                                            };
-                                            let _terminator_bytes = {
-                                                let mut term = [0u8; 3];
-                                                term.copy_from_slice(&bytes[(read_len - 3)..]);
-                                                term
-                                            };
-
-                                            debug!(
-                                                "Received {} bytes: {:02x?}",
-                                                bytes.len(),
-                                                bytes
-                                            );
-                                        }
-                                        Err(e) => {
-                                            info!("Error reading data: {:?}", e);
-
-                                            num_read_errors += 1;
-
-                                            if num_read_errors >= 3 {
-                                                debug!("Failed to read from device 3 times in a row. Closing this device handle.");
-                                                break;
-                                            } else {
-                                                continue;
-                                            }
-                                        }
-                                    }
+                                None => {
+                                    debug!(
+                                        "Report didn't contain a decodable barcode, ignoring it."
+                                    );
                                 }
                             }
-                            Err(e) => {
-                                info!("Error connecting to device: {:?}", e);
+                        }
+                        Err(e) => {
+                            info!("Error reading data: {:?}", e);
+
+                            num_read_errors += 1;
+
+                            if num_read_errors >= 3 {
+                                debug!("Failed to read from device 3 times in a row. Closing this device handle.");
+                                break;
+                            } else {
+                                continue;
                             }
                         }
+                    }
+                }
+
+                *self.device.lock().unwrap() = None;
+            }
+            Err(e) => {
+                info!("Error connecting to device: {:?}", e);
+            }
+        }
+    }
+
+    /// The original detection strategy: refresh the device list, check for a match, sleep,
+    /// repeat.
+    fn start_with_polling(&self, timeout: Duration) {
+        loop {
+            match initialize_hidapi() {
+                Ok(mut hidapi) => loop {
+                    let present_devices = refresh_devices(&mut hidapi);
+
+                    if present_devices.contains(&self.device_identifier) {
+                        info!("Device is present: {}", self.device_identifier);
+                        self.connect_and_read(&hidapi);
                     } else {
                         info!("Device {} not connected.", self.device_identifier);
                     }
@@ -133,8 +242,131 @@ impl<'a> UsbHidPosDeviceServer<'a> {
 
             info!("Retrying in {:?}.", timeout);
             sleep(timeout);
+        }
+    }
+
+    /// Waits for libusb hotplug arrival/left events matching `self.device_identifier` and
+    /// drives the read loop from them, instead of polling on a timer.
+    fn start_with_hotplug(&self, context: Context) {
+        let (tx, rx) = mpsc::channel::<DeviceEvent>();
+
+        let registration = HotplugBuilder::new()
+            .vendor_id(self.device_identifier.vendor_id())
+            .product_id(self.device_identifier.product_id())
+            .enumerate(true)
+            .register(
+                &context,
+                Box::new(HotplugHandler::new(self.device_identifier, tx)),
+            );
+
+        let _registration = match registration {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Failed to register a hotplug callback: {:?}.", e);
+                return;
+            }
+        };
+
+        let event_context = context.clone();
+        thread::spawn(move || loop {
+            if let Err(e) = event_context.handle_events(None) {
+                error!("Error handling libusb hotplug events: {:?}.", e);
+                break;
+            }
+        });
+
+        loop {
+            match rx.recv() {
+                Ok(DeviceEvent::Arrived) => {
+                    info!("Device arrived: {}", self.device_identifier);
+
+                    match initialize_hidapi() {
+                        Ok(hidapi) => self.connect_and_read(&hidapi),
+                        Err(e) => error!("Failed to initialize hidapi: {:?}.", e),
+                    }
+                }
+                Ok(DeviceEvent::Left) => {
+                    info!("Device left: {}", self.device_identifier);
+                }
+                Err(_) => {
+                    error!("Hotplug event channel closed, stopping.");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// An arrival or departure of the device we're watching for, as reported by libusb hotplug.
+enum DeviceEvent {
+    Arrived,
+    Left,
+}
+
+/// Matches libusb hotplug events against a [`UsbDeviceIdentifier`] and forwards the ones that
+/// match through a channel.
+///
+/// Owns a copy of the identifying fields (rather than borrowing the `'a` lifetime of
+/// [`UsbDeviceIdentifier`]) so it can be registered for the `'static` lifetime libusb's
+/// callback requires.
+struct HotplugHandler {
+    vid: u16,
+    pid: u16,
+    sn: Option<String>,
+    sender: mpsc::Sender<DeviceEvent>,
+}
+
+impl HotplugHandler {
+    fn new(device_identifier: UsbDeviceIdentifier<'_>, sender: mpsc::Sender<DeviceEvent>) -> Self {
+        HotplugHandler {
+            vid: device_identifier.vendor_id(),
+            pid: device_identifier.product_id(),
+            sn: device_identifier.serial_number().map(str::to_owned),
+            sender,
+        }
+    }
+
+    fn matches<T: UsbContext>(&self, device: &Device<T>) -> bool {
+        let device_desc = match device.device_descriptor() {
+            Ok(d) => d,
+            Err(_) => return false,
+        };
+
+        if device_desc.vendor_id() != self.vid || device_desc.product_id() != self.pid {
+            return false;
+        }
+
+        match &self.sn {
+            None => true,
+            Some(expected_sn) => {
+                let timeout = Duration::from_secs(1);
+
+                device
+                    .open()
+                    .ok()
+                    .and_then(|handle| {
+                        let language = handle.read_languages(timeout).ok()?.into_iter().next()?;
+                        handle
+                            .read_serial_number_string(language, &device_desc, timeout)
+                            .ok()
+                    })
+                    .map(|sn| &sn == expected_sn)
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+
+impl Hotplug<Context> for HotplugHandler {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        if self.matches(&device) {
+            let _ = self.sender.send(DeviceEvent::Arrived);
+        }
+    }
 
-            continue;
+    fn device_left(&mut self, device: Device<Context>) {
+        if self.matches(&device) {
+            let _ = self.sender.send(DeviceEvent::Left);
         }
     }
 }
@@ -9,7 +9,8 @@ use crate::devices::UsbDeviceIdentifier;
 /// If `RUST_LOG` is not set, this function will set the global default logging level to `info`,
 /// and for `server_usb` it will set the `trace` logging level.
 ///
-/// Log messages are formatted and printed to standard output by `tracing_subscriber::FmtSubscriber`.
+/// Log messages are formatted and printed to standard error by `tracing_subscriber::FmtSubscriber`,
+/// keeping standard output free for machine-readable data (e.g. `--list`'s JSON report).
 ///
 /// # Panics
 ///
@@ -20,7 +21,9 @@ pub fn initialize_logging(json_output: bool) {
     if std::env::var_os("RUST_LOG").is_none() {
         std::env::set_var("RUST_LOG", "info,server_usb=trace");
     }
-    let subscriber = FmtSubscriber::builder().with_max_level(Level::TRACE);
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::TRACE)
+        .with_writer(std::io::stderr);
     if json_output {
         subscriber.json().init();
     } else {
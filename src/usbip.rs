@@ -0,0 +1,479 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use rusb::{TransferType, UsbContext};
+use tracing::{debug, error, info};
+
+use crate::device::usb::{Endpoint, UsbDeviceHandle};
+
+/// The TCP port the USB/IP protocol listens on.
+pub const USBIP_PORT: u16 = 3240;
+
+/// The largest OUT transfer we'll allocate a buffer for in a single `USBIP_CMD_SUBMIT`.
+///
+/// `transfer_buffer_length` is client-controlled; without a cap, any peer on the LAN could
+/// force a multi-gigabyte allocation with one crafted packet. This well exceeds any real HID
+/// report or control transfer this scanner would ever send.
+const MAX_TRANSFER_BUFFER_LENGTH: u32 = 1024 * 1024;
+
+/// USB/IP opcodes and reply codes. All multi-byte integers on the wire are big-endian.
+mod opcode {
+    pub const OP_REQ_DEVLIST: u16 = 0x8005;
+    pub const OP_REP_DEVLIST: u16 = 0x0005;
+    pub const OP_REQ_IMPORT: u16 = 0x8003;
+    pub const OP_REP_IMPORT: u16 = 0x0003;
+
+    pub const USBIP_CMD_SUBMIT: u32 = 0x0001;
+    pub const USBIP_RET_SUBMIT: u32 = 0x0003;
+    pub const USBIP_CMD_UNLINK: u32 = 0x0002;
+    pub const USBIP_RET_UNLINK: u32 = 0x0004;
+}
+
+/// Forwards a single USB/IP URB to whatever can actually service it, so the wire protocol
+/// handling in [`UsbIpServer`] can stay independent of how the URB is serviced. A
+/// pseudo/virtual implementation can stand in for the real device in tests.
+pub trait UsbInterfaceHandler {
+    /// Services one URB (an IN or OUT transfer, or a control transfer when `setup_packet` is
+    /// non-zero) and returns the data to send back to the client. For OUT transfers this is
+    /// empty.
+    fn handle_urb(&mut self, endpoint: u8, setup_packet: &[u8; 8], out_data: &[u8]) -> Vec<u8>;
+}
+
+/// Forwards URBs to a real, locally-attached USB device through `rusb`.
+pub struct RusbInterfaceHandler<T: UsbContext> {
+    usb_device_handle: UsbDeviceHandle<T>,
+    endpoint: Endpoint,
+    timeout: Duration,
+}
+
+impl<T: UsbContext> RusbInterfaceHandler<T> {
+    pub fn new(
+        usb_device_handle: UsbDeviceHandle<T>,
+        endpoint: Endpoint,
+        timeout: Duration,
+    ) -> Self {
+        RusbInterfaceHandler {
+            usb_device_handle,
+            endpoint,
+            timeout,
+        }
+    }
+}
+
+impl<T: UsbContext> UsbInterfaceHandler for RusbInterfaceHandler<T> {
+    #[tracing::instrument(skip(self, out_data))]
+    fn handle_urb(&mut self, endpoint: u8, setup_packet: &[u8; 8], out_data: &[u8]) -> Vec<u8> {
+        // A real client always brings the virtual device up with standard control transfers
+        // (GET_DESCRIPTOR, SET_CONFIGURATION, ...) against endpoint 0 before ever touching the
+        // HID data endpoint. Those have to go through a control transfer regardless of what
+        // `self.endpoint` is, so route on the endpoint the client actually targeted (and,
+        // belt-and-braces, a non-zero setup_packet) rather than on `self.endpoint`'s type.
+        let is_control = endpoint & 0x7f == 0 || setup_packet.iter().any(|&b| b != 0);
+
+        if is_control {
+            return self.handle_control_transfer(setup_packet, out_data);
+        }
+
+        let is_write = !out_data.is_empty();
+        let mut buf = vec![0u8; 4096];
+
+        let result = match self.endpoint.transfer_type() {
+            TransferType::Interrupt if is_write => self
+                .usb_device_handle
+                .handle
+                .write_interrupt(self.endpoint.address(), out_data, self.timeout)
+                .map(|_| 0),
+            TransferType::Interrupt => self.usb_device_handle.handle.read_interrupt(
+                self.endpoint.address(),
+                &mut buf,
+                self.timeout,
+            ),
+            TransferType::Bulk if is_write => self
+                .usb_device_handle
+                .handle
+                .write_bulk(self.endpoint.address(), out_data, self.timeout)
+                .map(|_| 0),
+            TransferType::Bulk => self.usb_device_handle.handle.read_bulk(
+                self.endpoint.address(),
+                &mut buf,
+                self.timeout,
+            ),
+            TransferType::Control => {
+                return self.handle_control_transfer(setup_packet, out_data);
+            }
+            TransferType::Isochronous => {
+                error!("Isochronous transfers aren't supported for forwarding.");
+                return Vec::new();
+            }
+        };
+
+        match result {
+            Ok(len) => {
+                buf.truncate(len);
+                buf
+            }
+            Err(e) => {
+                error!("Failed to forward URB to the device: {:?}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl<T: UsbContext> RusbInterfaceHandler<T> {
+    /// Forwards a control transfer (targeting endpoint 0) to the device, independent of which
+    /// data endpoint was cached at startup. Direction is taken from `bmRequestType`'s top bit,
+    /// per the USB control transfer spec.
+    fn handle_control_transfer(&mut self, setup_packet: &[u8; 8], out_data: &[u8]) -> Vec<u8> {
+        let request_type = setup_packet[0];
+        let request = setup_packet[1];
+        let value = u16::from_le_bytes([setup_packet[2], setup_packet[3]]);
+        let index = u16::from_le_bytes([setup_packet[4], setup_packet[5]]);
+
+        const DIRECTION_IN: u8 = 0x80;
+        let result = if request_type & DIRECTION_IN != 0 {
+            let mut buf = vec![0u8; 4096];
+            self.usb_device_handle
+                .handle
+                .read_control(request_type, request, value, index, &mut buf, self.timeout)
+                .map(|len| {
+                    buf.truncate(len);
+                    buf
+                })
+        } else {
+            self.usb_device_handle
+                .handle
+                .write_control(request_type, request, value, index, out_data, self.timeout)
+                .map(|_| Vec::new())
+        };
+
+        match result {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to forward control transfer to the device: {:?}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// A device exported over USB/IP: the bus id a client imports by, and its descriptor
+/// information, as reported in `OP_REP_DEVLIST`/`OP_REP_IMPORT` replies.
+#[derive(Debug, Clone)]
+pub struct ExportedDevice {
+    pub bus_id: String,
+    pub path: String,
+    pub bus_num: u32,
+    pub dev_num: u32,
+    pub speed: u32,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub bcd_device: u16,
+    pub device_class: u8,
+    pub device_subclass: u8,
+    pub device_protocol: u8,
+    pub configuration_value: u8,
+    pub num_configurations: u8,
+    pub interfaces: Vec<(u8, u8, u8)>,
+}
+
+impl ExportedDevice {
+    /// Builds the descriptor info to export for an opened device, from its active (or first)
+    /// configuration.
+    pub fn from_handle<T: UsbContext>(
+        usb_device_handle: &UsbDeviceHandle<T>,
+    ) -> Option<ExportedDevice> {
+        let device = &usb_device_handle.device;
+        let device_desc = &usb_device_handle.device_desc;
+
+        let config_desc = device
+            .active_config_descriptor()
+            .or_else(|_| device.config_descriptor(0))
+            .ok()?;
+
+        let interfaces = config_desc
+            .interfaces()
+            .flat_map(|interface| interface.descriptors())
+            .map(|d| (d.class_code(), d.sub_class_code(), d.protocol_code()))
+            .collect();
+
+        let speed = match device.speed() {
+            rusb::Speed::Unknown => 0,
+            rusb::Speed::Low => 1,
+            rusb::Speed::Full => 2,
+            rusb::Speed::High => 3,
+            rusb::Speed::Super => 5,
+        };
+
+        Some(ExportedDevice {
+            bus_id: format!("{}-{}", device.bus_number(), device.address()),
+            path: format!(
+                "/sys/bus/usb/devices/{}-{}",
+                device.bus_number(),
+                device.address()
+            ),
+            bus_num: device.bus_number() as u32,
+            dev_num: device.address() as u32,
+            speed,
+            vendor_id: device_desc.vendor_id(),
+            product_id: device_desc.product_id(),
+            bcd_device: bcd(device_desc.device_version()),
+            device_class: device_desc.class_code(),
+            device_subclass: device_desc.sub_class_code(),
+            device_protocol: device_desc.protocol_code(),
+            configuration_value: config_desc.number(),
+            num_configurations: device_desc.num_configurations(),
+            interfaces,
+        })
+    }
+}
+
+/// Packs a `rusb::Version` into the 2-byte BCD form the USB descriptors use (e.g. `0x0200` for
+/// USB 2.0).
+fn bcd(version: rusb::Version) -> u16 {
+    ((version.major() as u16) << 8) | ((version.minor() as u16) << 4) | version.sub_minor() as u16
+}
+
+/// Serves a single, already-identified USB device over the USB/IP protocol, so a remote host
+/// can attach it as if it were local.
+pub struct UsbIpServer<H: UsbInterfaceHandler> {
+    device: ExportedDevice,
+    handler: H,
+}
+
+impl<H: UsbInterfaceHandler> UsbIpServer<H> {
+    pub fn new(device: ExportedDevice, handler: H) -> Self {
+        UsbIpServer { device, handler }
+    }
+
+    /// Listens for incoming USB/IP connections and serves them one at a time.
+    #[tracing::instrument(skip(self))]
+    pub fn start(&mut self, port: u16) -> io::Result<()> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+
+        info!("USB/IP server listening on port {}.", port);
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.handle_connection(stream) {
+                        error!("Error handling USB/IP connection: {:?}", e);
+                    }
+                }
+                Err(e) => error!("Failed to accept USB/IP connection: {:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_connection(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        info!("Accepted USB/IP connection from {:?}.", stream.peer_addr());
+
+        loop {
+            let mut header = [0u8; 4];
+            if stream.read_exact(&mut header).is_err() {
+                debug!("Client closed the connection.");
+                return Ok(());
+            }
+
+            let version_or_command = u32::from_be_bytes(header);
+
+            // The first two bytes of the USB/IP control exchange are a protocol version; the
+            // streaming command/reply headers instead start with a 4-byte command number. We
+            // only ever see the former before a successful OP_REQ_IMPORT, so dispatch on
+            // whichever one the four bytes we just read look like.
+            if version_or_command == opcode::USBIP_CMD_SUBMIT {
+                self.handle_cmd_submit(&mut stream)?;
+            } else if version_or_command == opcode::USBIP_CMD_UNLINK {
+                self.handle_cmd_unlink(&mut stream)?;
+            } else {
+                let code = u16::from_be_bytes([header[2], header[3]]);
+                self.handle_control_request(&mut stream, code)?;
+            }
+        }
+    }
+
+    fn handle_control_request(&mut self, stream: &mut TcpStream, code: u16) -> io::Result<()> {
+        let mut status = [0u8; 4];
+        stream.read_exact(&mut status)?;
+
+        match code {
+            opcode::OP_REQ_DEVLIST => {
+                info!("Handling OP_REQ_DEVLIST.");
+                self.reply_devlist(stream)
+            }
+            opcode::OP_REQ_IMPORT => {
+                info!("Handling OP_REQ_IMPORT.");
+                let mut bus_id = [0u8; 32];
+                stream.read_exact(&mut bus_id)?;
+                self.reply_import(stream, &bus_id)
+            }
+            other => {
+                error!("Unsupported USB/IP opcode: {:#06x}.", other);
+                Ok(())
+            }
+        }
+    }
+
+    fn reply_devlist(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let mut reply = Vec::new();
+        reply.extend_from_slice(&0x0111u16.to_be_bytes()); // version 1.1.1
+        reply.extend_from_slice(&opcode::OP_REP_DEVLIST.to_be_bytes());
+        reply.extend_from_slice(&0u32.to_be_bytes()); // status
+        reply.extend_from_slice(&1u32.to_be_bytes()); // ndevices
+        reply.extend_from_slice(&self.encode_device());
+
+        stream.write_all(&reply)
+    }
+
+    fn reply_import(
+        &mut self,
+        stream: &mut TcpStream,
+        requested_bus_id: &[u8; 32],
+    ) -> io::Result<()> {
+        let requested = String::from_utf8_lossy(requested_bus_id);
+        let requested = requested.trim_end_matches('\0');
+
+        let mut reply = Vec::new();
+        reply.extend_from_slice(&0x0111u16.to_be_bytes());
+        reply.extend_from_slice(&opcode::OP_REP_IMPORT.to_be_bytes());
+
+        if requested == self.device.bus_id {
+            reply.extend_from_slice(&0u32.to_be_bytes()); // status: success
+            reply.extend_from_slice(&self.encode_device_without_interfaces());
+        } else {
+            error!("OP_REQ_IMPORT requested unknown bus id: {}.", requested);
+            reply.extend_from_slice(&1u32.to_be_bytes()); // status: error
+        }
+
+        stream.write_all(&reply)
+    }
+
+    fn encode_device_without_interfaces(&self) -> Vec<u8> {
+        let mut buf = self.encode_device();
+        buf.truncate(buf.len() - self.device.interfaces.len() * 4);
+        buf
+    }
+
+    fn encode_device(&self) -> Vec<u8> {
+        let d = &self.device;
+        let mut buf = Vec::with_capacity(256 + 32 + 24 + d.interfaces.len() * 4);
+
+        let mut path = [0u8; 256];
+        let path_bytes = d.path.as_bytes();
+        path[..path_bytes.len().min(256)].copy_from_slice(&path_bytes[..path_bytes.len().min(256)]);
+        buf.extend_from_slice(&path);
+
+        let mut bus_id = [0u8; 32];
+        let bus_id_bytes = d.bus_id.as_bytes();
+        bus_id[..bus_id_bytes.len().min(32)]
+            .copy_from_slice(&bus_id_bytes[..bus_id_bytes.len().min(32)]);
+        buf.extend_from_slice(&bus_id);
+
+        buf.extend_from_slice(&d.bus_num.to_be_bytes());
+        buf.extend_from_slice(&d.dev_num.to_be_bytes());
+        buf.extend_from_slice(&d.speed.to_be_bytes());
+        buf.extend_from_slice(&d.vendor_id.to_be_bytes());
+        buf.extend_from_slice(&d.product_id.to_be_bytes());
+        buf.extend_from_slice(&d.bcd_device.to_be_bytes());
+        buf.push(d.device_class);
+        buf.push(d.device_subclass);
+        buf.push(d.device_protocol);
+        buf.push(d.configuration_value);
+        buf.push(d.num_configurations);
+        buf.push(d.interfaces.len() as u8);
+
+        for (class, subclass, protocol) in &d.interfaces {
+            buf.push(*class);
+            buf.push(*subclass);
+            buf.push(*protocol);
+            buf.push(0); // padding
+        }
+
+        buf
+    }
+
+    fn handle_cmd_submit(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        let mut rest = [0u8; 44];
+        stream.read_exact(&mut rest)?;
+
+        let seqnum = u32::from_be_bytes(rest[0..4].try_into().unwrap());
+        let _devid = u32::from_be_bytes(rest[4..8].try_into().unwrap());
+        let direction = u32::from_be_bytes(rest[8..12].try_into().unwrap());
+        let ep = u32::from_be_bytes(rest[12..16].try_into().unwrap());
+        let transfer_buffer_length = u32::from_be_bytes(rest[20..24].try_into().unwrap());
+
+        let mut setup_packet = [0u8; 8];
+        setup_packet.copy_from_slice(&rest[36..44]);
+
+        if transfer_buffer_length > MAX_TRANSFER_BUFFER_LENGTH {
+            error!(
+                "Rejecting SUBMIT with an oversized transfer_buffer_length of {} bytes.",
+                transfer_buffer_length
+            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "transfer_buffer_length exceeds the maximum allowed size",
+            ));
+        }
+
+        // direction: 0 = USBIP_DIR_OUT, 1 = USBIP_DIR_IN
+        let out_data = if direction == 0 {
+            let mut data = vec![0u8; transfer_buffer_length as usize];
+            stream.read_exact(&mut data)?;
+            data
+        } else {
+            Vec::new()
+        };
+
+        let endpoint_address = if direction == 0 {
+            ep as u8
+        } else {
+            ep as u8 | 0x80
+        };
+        let in_data = self
+            .handler
+            .handle_urb(endpoint_address, &setup_packet, &out_data);
+
+        let mut reply = Vec::new();
+        reply.extend_from_slice(&opcode::USBIP_RET_SUBMIT.to_be_bytes());
+        reply.extend_from_slice(&seqnum.to_be_bytes());
+        reply.extend_from_slice(&0u32.to_be_bytes()); // devid
+        reply.extend_from_slice(&direction.to_be_bytes());
+        reply.extend_from_slice(&ep.to_be_bytes());
+        reply.extend_from_slice(&0u32.to_be_bytes()); // status
+        reply.extend_from_slice(&(in_data.len() as u32).to_be_bytes()); // actual_length
+        reply.extend_from_slice(&0u32.to_be_bytes()); // start_frame
+        reply.extend_from_slice(&0u32.to_be_bytes()); // number_of_packets
+        reply.extend_from_slice(&0u32.to_be_bytes()); // error_count
+        reply.extend_from_slice(&[0u8; 8]); // padding
+        reply.extend_from_slice(&in_data);
+
+        stream.write_all(&reply)
+    }
+
+    fn handle_cmd_unlink(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        let mut rest = [0u8; 44];
+        stream.read_exact(&mut rest)?;
+
+        let seqnum = u32::from_be_bytes(rest[0..4].try_into().unwrap());
+        let devid = u32::from_be_bytes(rest[4..8].try_into().unwrap());
+        let direction = u32::from_be_bytes(rest[8..12].try_into().unwrap());
+        let ep = u32::from_be_bytes(rest[12..16].try_into().unwrap());
+
+        info!("Unlinking URB with seqnum {}.", seqnum);
+
+        let mut reply = Vec::new();
+        reply.extend_from_slice(&opcode::USBIP_RET_UNLINK.to_be_bytes());
+        reply.extend_from_slice(&seqnum.to_be_bytes());
+        reply.extend_from_slice(&devid.to_be_bytes());
+        reply.extend_from_slice(&direction.to_be_bytes());
+        reply.extend_from_slice(&ep.to_be_bytes());
+        reply.extend_from_slice(&0u32.to_be_bytes()); // status
+        reply.extend_from_slice(&[0u8; 24]); // padding
+
+        stream.write_all(&reply)
+    }
+}